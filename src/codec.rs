@@ -0,0 +1,237 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Serialization backends for [`Server`](../server/trait.Server.html).
+//!
+//! A [`Codec`](trait.Codec.html) controls what value type [`AbstractServer`](../server/struct.AbstractServer.html)
+//! boxes a `Server`'s results as, and how a raw wire frame is turned into a method name and
+//! params for dispatch: [`JsonCodec`](struct.JsonCodec.html) (the default, `serde_json::Value`)
+//! or [`MsgpackCodec`](struct.MsgpackCodec.html) (`rmpv::Value`, MessagePack's data model).
+//! There is still no framing or transport here, and [`decode`](trait.Codec.html#tymethod.decode)
+//! only understands a `{"method": ..., "params": ...}`-shaped frame, not a full JSON-RPC or
+//! MessagePack-RPC envelope (request ids, the `jsonrpc` version tag, distinguishing a request
+//! from a notification).
+//!
+//! [`Server::rpc`](../server/trait.Server.html#tymethod.rpc),
+//! [`Server::notification`](../server/trait.Server.html#tymethod.notification) and
+//! [`Server::subscription`](../server/trait.Server.html#tymethod.subscription) always take
+//! params as `&Option<serde_json::Value>`, no matter which `Codec` decoded them off the wire:
+//! [`Codec::params_to_json`](trait.Codec.html#tymethod.params_to_json) is what bridges the two,
+//! and [`AbstractServer`](../server/struct.AbstractServer.html)'s `dispatch_rpc`/
+//! `dispatch_notification`/`dispatch_subscription` call it centrally before delegating to the
+//! wrapped `Server`. So driving a `Server` impl over MessagePack-RPC with
+//! [`MsgpackCodec`](struct.MsgpackCodec.html) needs no hand-rolled transcoding: decode a frame,
+//! hand the `DecodedCall` straight to `AbstractServer::dispatch_rpc` (or its notification/
+//! subscription counterparts), and the `rmpv::Value` params are converted to JSON for you.
+
+use std::error;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json;
+use serde_json::Value;
+
+/// A method name and params decoded off the wire.
+///
+/// For [`JsonCodec`](struct.JsonCodec.html), `params` can be handed straight to
+/// [`Server::rpc`](../server/trait.Server.html#method.rpc) or
+/// [`Server::notification`](../server/trait.Server.html#method.notification). For any other
+/// codec, see the [module docs](index.html) on converting `params` to JSON first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedCall<V> {
+    /// The name of the method being called.
+    pub method: String,
+    /// The call's params, in the codec's value representation, if any were sent.
+    pub params: Option<V>,
+}
+
+/// An error returned by [`Codec::decode`](trait.Codec.html#tymethod.decode).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeError(String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "malformed RPC frame: {}", self.0)
+    }
+}
+
+impl error::Error for DecodeError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A serialization backend usable to carry a `Server`'s results.
+///
+/// Implement this to let [`AbstractServer`](../server/struct.AbstractServer.html) box a
+/// `Server`'s results as something other than JSON, and to teach it how to pull a method name
+/// and params out of that format's raw bytes.
+pub trait Codec {
+    /// The value type this codec's replies, notifications and decoded params are carried as.
+    type Value: Serialize + 'static;
+    /// Serializes a typed result into this codec's value representation.
+    ///
+    /// Like `serde_json::to_value`, this is expected to only fail for types that are not
+    /// representable by `serde`, which is considered a bug in the caller.
+    fn to_value<T: Serialize>(value: T) -> Self::Value;
+    /// Decodes a raw wire frame into the method name and params it carries.
+    fn decode(input: &[u8]) -> Result<DecodedCall<Self::Value>, DecodeError>;
+    /// Converts this codec's native params representation into the `serde_json::Value` that
+    /// [`Server::rpc`](../server/trait.Server.html#tymethod.rpc) and friends expect their params
+    /// in.
+    ///
+    /// This is what lets a `Server` implementation be driven over a non-JSON codec like
+    /// [`MsgpackCodec`](struct.MsgpackCodec.html) without hand-rolling the transcode: see
+    /// [`AbstractServer::dispatch_rpc`](../server/struct.AbstractServer.html#method.dispatch_rpc)
+    /// and its notification/subscription counterparts, which call this centrally.
+    fn params_to_json(value: Self::Value) -> Result<Value, DecodeError>;
+}
+
+/// The JSON-RPC 2.0 codec, used by default throughout this crate.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    type Value = Value;
+    fn to_value<T: Serialize>(value: T) -> Self::Value {
+        serde_json::to_value(value)
+            .expect("Your result type is not convertible to JSON, which is a bug")
+    }
+    fn decode(input: &[u8]) -> Result<DecodedCall<Self::Value>, DecodeError> {
+        let value: Value =
+            serde_json::from_slice(input).map_err(|e| DecodeError(e.to_string()))?;
+        let mut object = match value {
+            Value::Object(object) => object,
+            _ => return Err(DecodeError("expected a JSON object".to_owned())),
+        };
+        let method = match object.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => return Err(DecodeError("missing a \"method\" string field".to_owned())),
+        };
+        let params = object.remove("params");
+        Ok(DecodedCall { method, params })
+    }
+    fn params_to_json(value: Self::Value) -> Result<Value, DecodeError> {
+        Ok(value)
+    }
+}
+
+/// Boxes a `Server`'s results as `rmpv::Value`, MessagePack's data model, instead of JSON.
+pub struct MsgpackCodec;
+
+impl Codec for MsgpackCodec {
+    type Value = ::rmpv::Value;
+    fn to_value<T: Serialize>(value: T) -> Self::Value {
+        ::rmpv::ext::to_value(value)
+            .expect("Your result type is not convertible to MessagePack, which is a bug")
+    }
+    fn decode(input: &[u8]) -> Result<DecodedCall<Self::Value>, DecodeError> {
+        let value = ::rmpv::decode::read_value(&mut &*input)
+            .map_err(|e| DecodeError(e.to_string()))?;
+        let map = value
+            .as_map()
+            .ok_or_else(|| DecodeError("expected a MessagePack map".to_owned()))?;
+        let method = map.iter()
+            .find(|&(key, _)| key.as_str() == Some("method"))
+            .and_then(|(_, value)| value.as_str())
+            .ok_or_else(|| DecodeError("missing a \"method\" string field".to_owned()))?
+            .to_owned();
+        let params = map.iter()
+            .find(|&(key, _)| key.as_str() == Some("params"))
+            .map(|(_, value)| value.clone());
+        Ok(DecodedCall { method, params })
+    }
+    fn params_to_json(value: Self::Value) -> Result<Value, DecodeError> {
+        ::rmpv::ext::from_value(value).map_err(|e| DecodeError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `JsonCodec` carries values as plain `serde_json::Value`.
+    #[test]
+    fn json_codec_to_value() {
+        assert_eq!(serde_json::Value::from(42), JsonCodec::to_value(42));
+    }
+
+    /// `MsgpackCodec` carries the very same value as an `rmpv::Value` instead.
+    #[test]
+    fn msgpack_codec_to_value() {
+        assert_eq!(::rmpv::Value::from(42), MsgpackCodec::to_value(42));
+    }
+
+    /// `JsonCodec` pulls the method name and params out of a JSON frame.
+    #[test]
+    fn json_codec_decode_extracts_method_and_params() {
+        let decoded = JsonCodec::decode(br#"{"method": "add", "params": [1, 2]}"#).unwrap();
+        assert_eq!("add", decoded.method);
+        assert_eq!(Some(serde_json::json!([1, 2])), decoded.params);
+    }
+
+    /// Params are optional; a frame without them decodes to `None`.
+    #[test]
+    fn json_codec_decode_without_params() {
+        let decoded = JsonCodec::decode(br#"{"method": "ping"}"#).unwrap();
+        assert_eq!("ping", decoded.method);
+        assert_eq!(None, decoded.params);
+    }
+
+    /// A frame missing the `method` field is rejected instead of panicking.
+    #[test]
+    fn json_codec_decode_rejects_missing_method() {
+        assert!(JsonCodec::decode(br#"{"params": []}"#).is_err());
+    }
+
+    /// `MsgpackCodec` pulls the method name and params out of the same shaped frame, encoded as
+    /// MessagePack instead of JSON.
+    #[test]
+    fn msgpack_codec_decode_extracts_method_and_params() {
+        let mut input = Vec::new();
+        let frame = ::rmpv::Value::Map(vec![
+            (::rmpv::Value::from("method"), ::rmpv::Value::from("add")),
+            (
+                ::rmpv::Value::from("params"),
+                ::rmpv::Value::Array(vec![::rmpv::Value::from(1), ::rmpv::Value::from(2)]),
+            ),
+        ]);
+        ::rmpv::encode::write_value(&mut input, &frame).unwrap();
+        let decoded = MsgpackCodec::decode(&input).unwrap();
+        assert_eq!("add", decoded.method);
+        assert_eq!(
+            Some(::rmpv::Value::Array(vec![
+                ::rmpv::Value::from(1),
+                ::rmpv::Value::from(2),
+            ])),
+            decoded.params
+        );
+    }
+
+    /// A MessagePack frame missing the `method` field is rejected instead of panicking.
+    #[test]
+    fn msgpack_codec_decode_rejects_missing_method() {
+        let mut input = Vec::new();
+        let frame = ::rmpv::Value::Map(vec![]);
+        ::rmpv::encode::write_value(&mut input, &frame).unwrap();
+        assert!(MsgpackCodec::decode(&input).is_err());
+    }
+
+    /// `JsonCodec::params_to_json` is just the identity conversion.
+    #[test]
+    fn json_codec_params_to_json_is_identity() {
+        let params = serde_json::json!([1, 2]);
+        assert_eq!(Ok(params.clone()), JsonCodec::params_to_json(params));
+    }
+
+    /// `MsgpackCodec::params_to_json` turns decoded `rmpv::Value` params into the equivalent
+    /// `serde_json::Value`, which is what lets a `Server` consume them unmodified.
+    #[test]
+    fn msgpack_codec_params_to_json_converts() {
+        let params = ::rmpv::Value::Array(vec![::rmpv::Value::from(1), ::rmpv::Value::from(2)]);
+        assert_eq!(Ok(serde_json::json!([1, 2])), MsgpackCodec::params_to_json(params));
+    }
+}
@@ -0,0 +1,635 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The endpoint that drives a [`Server`](../server/trait.Server.html).
+//!
+//! [`ServerCtl`](struct.ServerCtl.html) is the handle a `Server` gets to talk back to its
+//! endpoint: terminate the connection, push a subscription item, cancel an in-flight call. The
+//! free functions here ([`abortable`](fn.abortable.html)/[`abortable_stream`](fn.abortable_stream.html))
+//! are the plumbing [`Endpoint`](struct.Endpoint.html) uses to make calls and subscriptions
+//! actually cancellable.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{Async, Future, IntoFuture, Poll, Stream};
+use futures::future::Either;
+use futures::sync::{mpsc, oneshot};
+use serde_json::Value;
+
+use message::RPCError;
+use server::Server;
+
+/// The id of a request, as sent by the client. Requests and subscriptions share the id space.
+pub type RequestId = u64;
+/// The id of an active subscription, handed back to the client as the reply to the subscribe
+/// call.
+pub type SubscriptionId = u64;
+/// The stream of outgoing notifications a transport must drain and write to the wire: subscription
+/// items, final `unsubscribe` notifications, and anything else pushed through
+/// [`ServerCtl::notify`](struct.ServerCtl.html#method.notify).
+pub type Outgoing = mpsc::UnboundedReceiver<(String, Value)>;
+
+/// What's tracked for a single in-flight call: its cancellation signal and the deadline it was
+/// dispatched with, if any.
+///
+/// Keeping the deadline here, keyed by [`RequestId`](type.RequestId.html), is what lets two calls
+/// in flight at once each see their own deadline through a [`ServerCtl`](struct.ServerCtl.html)
+/// scoped to them, instead of a single slot shared (and clobbered) by every call on the
+/// connection.
+///
+/// The endpoint itself never reads this once it's stored: there is no timer here that cancels
+/// the call once `deadline` elapses. It's advisory, for a handler that wants to check it; see
+/// [`ServerCtl::deadline`](struct.ServerCtl.html#method.deadline).
+struct CallState {
+    cancel: oneshot::Sender<()>,
+    deadline: Option<Duration>,
+}
+
+struct Inner {
+    terminated: Cell<bool>,
+    dropped: RefCell<Option<oneshot::Sender<()>>>,
+    killed: RefCell<Option<oneshot::Sender<()>>>,
+    calls: RefCell<HashMap<RequestId, CallState>>,
+    subscriptions: RefCell<HashMap<SubscriptionId, oneshot::Sender<()>>>,
+    next_subscription: Cell<SubscriptionId>,
+    outgoing: mpsc::UnboundedSender<(String, Value)>,
+}
+
+/// A handle the [`Server`](../server/trait.Server.html) uses to talk back to its endpoint.
+///
+/// One `ServerCtl` is shared by a whole connection; it's passed into every callback of the
+/// `Server` trait. The handle passed into [`Server::rpc`](../server/trait.Server.html#tymethod.rpc)
+/// for a particular call is additionally scoped to that call, so its
+/// [`deadline`](#method.deadline) reads back only that call's own deadline (see
+/// [`Endpoint::call`](struct.Endpoint.html#method.call)).
+#[derive(Clone)]
+pub struct ServerCtl(Rc<Inner>, Option<RequestId>);
+
+impl ServerCtl {
+    /// Creates a fresh `ServerCtl`.
+    ///
+    /// Returns the control handle, the futures that resolve once
+    /// [`terminate`](#method.terminate) and [`kill`](#method.kill) are requested, and the stream
+    /// of outgoing notifications (subscription items, final `unsubscribe` notifications, and
+    /// anything else pushed through [`notify`](#method.notify)) a real transport must drain and
+    /// write to the wire.
+    pub(crate) fn new() -> (Self, oneshot::Receiver<()>, oneshot::Receiver<()>, Outgoing) {
+        let (dropped_tx, dropped_rx) = oneshot::channel();
+        let (killed_tx, killed_rx) = oneshot::channel();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded();
+        let inner = Inner {
+            terminated: Cell::new(false),
+            dropped: RefCell::new(Some(dropped_tx)),
+            killed: RefCell::new(Some(killed_tx)),
+            calls: RefCell::new(HashMap::new()),
+            subscriptions: RefCell::new(HashMap::new()),
+            next_subscription: Cell::new(0),
+            outgoing: outgoing_tx,
+        };
+        (ServerCtl(Rc::new(inner), None), dropped_rx, killed_rx, outgoing_rx)
+    }
+    /// Creates a `ServerCtl` detached from any real connection, for use in tests.
+    ///
+    /// Returns the control handle together with the futures that resolve once
+    /// [`terminate`](#method.terminate) and [`kill`](#method.kill) are requested, respectively.
+    /// Outgoing notifications are discarded; use [`new`](#method.new) directly if the test needs
+    /// to observe them.
+    pub fn new_test() -> (Self, oneshot::Receiver<()>, oneshot::Receiver<()>) {
+        let (ctl, dropped, killed, _outgoing) = ServerCtl::new();
+        (ctl, dropped, killed)
+    }
+    /// Terminates the connection from the server side.
+    ///
+    /// All calls and subscriptions still in flight are cancelled without a reply.
+    pub fn terminate(&self) {
+        if self.0.terminated.replace(true) {
+            return;
+        }
+        if let Some(sender) = self.0.dropped.borrow_mut().take() {
+            let _ = sender.send(());
+        }
+        for (_, call) in self.0.calls.borrow_mut().drain() {
+            let _ = call.cancel.send(());
+        }
+        // Go through `close_subscription` instead of draining the map and sending the raw
+        // cancel signal ourselves: draining first would make `abortable_stream`'s own
+        // completion handler find the entry already gone (see `close_subscription`'s "already
+        // removed" guard) and skip the final `unsubscribe` notification.
+        let ids: Vec<SubscriptionId> = self.0.subscriptions.borrow().keys().cloned().collect();
+        for id in ids {
+            self.close_subscription(id);
+        }
+    }
+    /// Forcefully tears down the connection, without waiting for anything to wind down.
+    ///
+    /// Unlike [`terminate`](#method.terminate), which is the graceful, server-initiated shutdown,
+    /// this is for the case where the endpoint gives up on the connection (e.g. a fatal transport
+    /// error). It still cancels every in-flight call and subscription.
+    pub fn kill(&self) {
+        if let Some(sender) = self.0.killed.borrow_mut().take() {
+            let _ = sender.send(());
+        }
+        self.terminate();
+    }
+    /// The duration the call this `ServerCtl` is scoped to was dispatched with, if the endpoint
+    /// was given one.
+    ///
+    /// This is advisory only: it's the duration handed to
+    /// [`Endpoint::call`](struct.Endpoint.html#method.call) verbatim, not a countdown, and the
+    /// endpoint itself never reads it back or enforces it -- there is no timer here that cancels
+    /// the call once it elapses. A handler performing long-running work can use it (together with
+    /// its own notion of when the call started) to bail out early instead of running forever;
+    /// actually enforcing "the client no longer cares after N seconds" is the transport's job,
+    /// e.g. by calling [`cancel_call`](#method.cancel_call) itself once its own timer fires.
+    /// Returns `None` for a `ServerCtl` that isn't scoped to a particular call (e.g. the one a
+    /// notification or subscription handler receives).
+    pub fn deadline(&self) -> Option<Duration> {
+        let id = self.1?;
+        self.0.calls.borrow().get(&id).and_then(|call| call.deadline)
+    }
+    /// Registers a just-started call as cancellable, recording the deadline it was dispatched
+    /// with.
+    ///
+    /// Returns a `ServerCtl` scoped to this call -- so its [`deadline`](#method.deadline) reads
+    /// back only this call's own slot, never one clobbered by another call dispatched while this
+    /// one is still in flight -- together with the [`AbortHandle`](struct.AbortHandle.html) the
+    /// endpoint keeps around to cancel it later (client disconnect, explicit cancel
+    /// notification).
+    pub fn register_call(&self, id: RequestId, deadline: Option<Duration>) -> (ServerCtl, AbortHandle) {
+        let (tx, rx) = oneshot::channel();
+        self.0.calls.borrow_mut().insert(id, CallState { cancel: tx, deadline });
+        let scoped = ServerCtl(self.0.clone(), Some(id));
+        let handle = AbortHandle(AbortHandleInner::Call(self.clone(), id, Some(rx)));
+        (scoped, handle)
+    }
+    /// Cancels the call with the given request id, if it's still in flight.
+    ///
+    /// The future driving it is dropped without ever completing, and no reply is sent.
+    pub fn cancel_call(&self, id: RequestId) {
+        if let Some(call) = self.0.calls.borrow_mut().remove(&id) {
+            let _ = call.cancel.send(());
+        }
+    }
+    /// Allocates a fresh subscription id and registers it as cancellable.
+    pub fn open_subscription(&self) -> (SubscriptionId, AbortHandle) {
+        let id = self.0.next_subscription.get();
+        self.0.next_subscription.set(id + 1);
+        let (tx, rx) = oneshot::channel();
+        self.0.subscriptions.borrow_mut().insert(id, tx);
+        (id, AbortHandle(AbortHandleInner::Subscription(self.clone(), id, Some(rx))))
+    }
+    /// Closes a subscription: the stream backing it is dropped and a final unsubscribe
+    /// notification is sent to the client.
+    ///
+    /// This is what a `Server`'s `rpc` handler for the `unsubscribe` method should call with the
+    /// id the client gives back; the endpoint also calls it on the server's behalf whenever the
+    /// subscription's stream ends by itself or the connection goes away.
+    pub fn close_subscription(&self, id: SubscriptionId) {
+        if self.0.subscriptions.borrow_mut().remove(&id).is_some() {
+            self.notify("unsubscribe", json_subscription(id));
+        }
+    }
+    /// Pushes a notification to the client.
+    ///
+    /// Used internally to forward subscription items and the final unsubscribe notification; not
+    /// normally needed by a `Server` implementation directly. Queues onto the outgoing stream a
+    /// real transport was handed by [`new`](#method.new); if nothing is left to drain it (the
+    /// connection is gone), the notification is silently dropped, same as writing to a closed
+    /// socket would be.
+    pub fn notify(&self, method: &str, params: Value) {
+        let _ = self.0.outgoing.unbounded_send((method.to_owned(), params));
+    }
+}
+
+fn json_subscription(id: SubscriptionId) -> Value {
+    let mut obj = ::serde_json::Map::new();
+    obj.insert("subscription".to_owned(), Value::from(id));
+    Value::Object(obj)
+}
+
+/// Builds the params of the `subscription` notification sent when a subscription's stream
+/// itself resolves to an error, so the client learns the subscription died instead of just
+/// seeing it vanish. Immediately followed by the usual final `unsubscribe` notification.
+fn json_subscription_error(id: SubscriptionId, err: &RPCError) -> Value {
+    let mut error = ::serde_json::Map::new();
+    error.insert("code".to_owned(), Value::from(err.code()));
+    error.insert("message".to_owned(), Value::from(err.message()));
+    if let Some(data) = err.data() {
+        error.insert("data".to_owned(), data.clone());
+    }
+    let mut obj = ::serde_json::Map::new();
+    obj.insert("subscription".to_owned(), Value::from(id));
+    obj.insert("error".to_owned(), Value::Object(error));
+    Value::Object(obj)
+}
+
+enum AbortHandleInner {
+    Call(ServerCtl, RequestId, Option<oneshot::Receiver<()>>),
+    Subscription(ServerCtl, SubscriptionId, Option<oneshot::Receiver<()>>),
+}
+
+/// A handle tying a running call or subscription to its cancellation signal.
+///
+/// Obtained from [`ServerCtl::register_call`](struct.ServerCtl.html#method.register_call) or
+/// [`ServerCtl::open_subscription`](struct.ServerCtl.html#method.open_subscription); pass it to
+/// [`abortable`](fn.abortable.html)/[`abortable_stream`](fn.abortable_stream.html) to actually make
+/// the call or subscription stop being polled once it's cancelled or closed.
+pub struct AbortHandle(AbortHandleInner);
+
+impl AbortHandle {
+    fn take_receiver(&mut self) -> oneshot::Receiver<()> {
+        match self.0 {
+            AbortHandleInner::Call(_, _, ref mut rx) |
+            AbortHandleInner::Subscription(_, _, ref mut rx) => {
+                rx.take().expect("AbortHandle's receiver used twice")
+            }
+        }
+    }
+}
+
+impl Drop for AbortHandle {
+    fn drop(&mut self) {
+        match self.0 {
+            AbortHandleInner::Call(ref ctl, id, _) => ctl.cancel_call(id),
+            AbortHandleInner::Subscription(ref ctl, id, _) => ctl.close_subscription(id),
+        }
+    }
+}
+
+/// Wraps `future` so `handle` can stop it from being polled any further.
+///
+/// Once `handle`'s call is cancelled, `future` is dropped without resolving and the returned
+/// future resolves to [`RPCError::cancelled`](../message/struct.RPCError.html#method.cancelled).
+/// This is how the endpoint guarantees no reply is ever sent for a cancelled request.
+pub fn abortable<F>(future: F, mut handle: AbortHandle) -> Box<dyn Future<Item = F::Item, Error = RPCError>>
+    where F: Future<Error = RPCError> + 'static,
+          F::Item: 'static
+{
+    let cancel = handle.take_receiver();
+    let guarded = future.select2(cancel).then(move |result| {
+        // Keep the handle alive until `future` actually settles; dropping it any earlier (e.g.
+        // right after this function returns) would cancel the call before it's even polled.
+        let _handle = handle;
+        match result {
+            Ok(Either::A((item, _))) => Ok(item),
+            Ok(Either::B((_, _))) => Err(RPCError::cancelled()),
+            Err(Either::A((err, _))) => Err(err),
+            Err(Either::B((_, _))) => Err(RPCError::cancelled()),
+        }
+    });
+    Box::new(guarded)
+}
+
+/// The subscription-side cancellation plumbing: forwards every item of `stream` to `ctl` as a
+/// `subscription` notification carrying `id`, until the stream ends on its own, resolves to an
+/// error, or the handle is closed. If the stream errs, that error is forwarded to the client as
+/// one last `subscription` notification carrying an `error` member (instead of `result`) before
+/// the subscription closes, so a failing stream doesn't look indistinguishable from one that
+/// simply ran out of items. Either way,
+/// [`ServerCtl::close_subscription`](struct.ServerCtl.html#method.close_subscription) is called
+/// exactly once so a dropped subscriber never leaks a spinning task.
+pub fn abortable_stream<S>(stream: S, ctl: ServerCtl, id: SubscriptionId, mut handle: AbortHandle)
+                           -> Box<dyn Future<Item = (), Error = ()>>
+    where S: Stream<Error = RPCError> + 'static,
+          S::Item: ::serde::Serialize + 'static
+{
+    let cancel = handle.take_receiver();
+    let forward = ForwardSubscription {
+        stream,
+        ctl: ctl.clone(),
+        id,
+    };
+    let guarded = forward.select2(cancel).then(move |_| {
+        // Keep the handle alive until the stream is actually done forwarding; dropping it
+        // any earlier would fire the unsubscribe notification before the last items do.
+        let _handle = handle;
+        ctl.close_subscription(id);
+        Ok(())
+    });
+    Box::new(guarded)
+}
+
+struct ForwardSubscription<S> {
+    stream: S,
+    ctl: ServerCtl,
+    id: SubscriptionId,
+}
+
+impl<S> Future for ForwardSubscription<S>
+    where S: Stream<Error = RPCError>,
+          S::Item: ::serde::Serialize
+{
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.stream.poll() {
+                Ok(Async::Ready(Some(item))) => {
+                    let value = ::serde_json::to_value(item)
+                        .expect("Your result type is not convertible to JSON, which is a bug");
+                    let mut params = ::serde_json::Map::new();
+                    params.insert("subscription".to_owned(), Value::from(self.id));
+                    params.insert("result".to_owned(), value);
+                    self.ctl.notify("subscription", Value::Object(params));
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    self.ctl.notify("subscription", json_subscription_error(self.id, &err));
+                    return Ok(Async::Ready(()));
+                }
+            }
+        }
+    }
+}
+
+/// Drives a [`Server`](../server/trait.Server.html): the thing a transport hands subscribe calls,
+/// ordinary calls and notifications to.
+///
+/// `call` and `subscribe` register what they start with the `ServerCtl` they hand to the server,
+/// so it can later be cancelled (client disconnect, explicit cancel notification, unsubscribe); a
+/// real transport still has to poll the futures they return itself (e.g. by spawning them) and
+/// feed whatever they produce out over the wire.
+pub struct Endpoint<S> {
+    server: S,
+    ctl: ServerCtl,
+}
+
+impl<S: Server> Endpoint<S> {
+    /// Creates an endpoint around `server`, detached from any real connection.
+    ///
+    /// Returns the control handle's `dropped`/`killed` futures alongside the endpoint, as with
+    /// [`ServerCtl::new`](struct.ServerCtl.html#method.new), plus the stream of outgoing
+    /// notifications (subscription items, `unsubscribe` notifications, anything else pushed
+    /// through [`ServerCtl::notify`](struct.ServerCtl.html#method.notify)); a real transport must
+    /// poll and drain that stream, writing each item out over the wire, and wire `dropped`/
+    /// `killed` into the connection's own teardown.
+    pub fn new(server: S) -> (Self, oneshot::Receiver<()>, oneshot::Receiver<()>, Outgoing) {
+        let (ctl, dropped, killed, outgoing) = ServerCtl::new();
+        server.initialized(&ctl);
+        (Endpoint { server, ctl }, dropped, killed, outgoing)
+    }
+    /// The `ServerCtl` this endpoint hands to its `Server`'s callbacks.
+    pub fn ctl(&self) -> &ServerCtl {
+        &self.ctl
+    }
+    /// Subscribes to `method`, as if a client had just sent the originating call.
+    ///
+    /// On success, returns the subscription id to reply to the client with and the future that
+    /// forwards the stream's items as `subscription` notifications until it ends or is closed;
+    /// the caller is responsible for driving that future (e.g. by spawning it). Returns `None` if
+    /// `method` isn't known, exactly like
+    /// [`Server::subscription`](../server/trait.Server.html#tymethod.subscription) does.
+    pub fn subscribe(&self, method: &str, params: &Option<Value>)
+                     -> Option<(SubscriptionId, Box<dyn Future<Item = (), Error = ()>>)>
+        where S::Success: 'static
+    {
+        self.server.subscription(&self.ctl, method, params).map(|stream| {
+            let (id, handle) = self.ctl.open_subscription();
+            (id, abortable_stream(stream, self.ctl.clone(), id, handle))
+        })
+    }
+    /// Delivers a notification to the wrapped `Server`, as if a client had just sent it.
+    pub fn notification(&self, method: &str, params: &Option<Value>) -> Option<S::NotificationResult> {
+        self.server.notification(&self.ctl, method, params)
+    }
+    /// Calls `method` on the wrapped `Server`, as if a client had just sent the request with id
+    /// `id`.
+    ///
+    /// The returned future is registered as cancellable under `id` (see
+    /// [`ServerCtl::cancel_call`](struct.ServerCtl.html#method.cancel_call)) and, if `deadline` is
+    /// given, made visible through
+    /// [`ServerCtl::deadline`](struct.ServerCtl.html#method.deadline) for as long as it's driven,
+    /// even if other calls are dispatched concurrently on this same connection. Returns `None` if
+    /// `method` isn't known, exactly like
+    /// [`Server::rpc`](../server/trait.Server.html#tymethod.rpc) does.
+    pub fn call(&self, id: RequestId, method: &str, params: &Option<Value>, deadline: Option<Duration>)
+               -> Option<Box<dyn Future<Item = S::Success, Error = RPCError>>>
+        where S::Success: 'static
+    {
+        let (call_ctl, handle) = self.ctl.register_call(id, deadline);
+        self.server
+            .rpc(&call_ctl, method, params)
+            .map(|result| abortable(result.into_future(), handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::executor::{self, Notify};
+    use futures::stream;
+
+    use super::*;
+
+    /// A `Notify` that does nothing, for polling a stream outside of a real task context.
+    struct NoopNotify;
+
+    impl Notify for NoopNotify {
+        fn notify(&self, _id: usize) {}
+    }
+
+    /// Drains whatever is currently queued on an outgoing notification stream without blocking,
+    /// for asserting on in tests.
+    fn drain(outgoing: Outgoing) -> Vec<(String, Value)> {
+        let notify: executor::NotifyHandle = Arc::new(NoopNotify).into();
+        let mut spawned = executor::spawn(outgoing);
+        let mut items = Vec::new();
+        while let Ok(Async::Ready(Some(item))) = spawned.poll_stream_notify(&notify, 0) {
+            items.push(item);
+        }
+        items
+    }
+
+    /// `terminate` resolves the `dropped` future and cancels outstanding calls/subscriptions.
+    /// A call whose future has already resolved still yields its own result.
+    #[test]
+    fn terminate_cancels_everything() {
+        let (ctl, dropped, _killed) = ServerCtl::new_test();
+        let (_call_ctl, call_handle) = ctl.register_call(1, None);
+        let (_call_ctl2, handle2) = ctl.register_call(2, None);
+        let sub_handle = ctl.open_subscription().1;
+        let call = abortable(Ok::<_, RPCError>(()).into_future(), call_handle);
+        ctl.terminate();
+        dropped.wait().unwrap();
+        // `terminate` already fired call 2's cancel signal, so its never-resolving future is
+        // reported as cancelled instead of hanging.
+        let pending: Box<dyn Future<Item = (), Error = RPCError>> =
+            Box::new(::futures::future::empty());
+        let result = abortable(pending, handle2).wait();
+        assert_eq!(Err(RPCError::cancelled()), result);
+        assert!(call.wait().is_ok());
+        drop(sub_handle);
+    }
+
+    /// A subscription forwards stream items and closes with a final notification, both observable
+    /// on the endpoint's outgoing notification stream.
+    #[test]
+    fn subscription_forwards_items_then_closes() {
+        let (ctl, _dropped, _killed, outgoing) = ServerCtl::new();
+        let (id, handle) = ctl.open_subscription();
+        let items = stream::iter_ok::<_, RPCError>(vec![1u32, 2, 3]);
+        abortable_stream(items, ctl.clone(), id, handle).wait().unwrap();
+        let sent = drain(outgoing);
+        assert_eq!(4, sent.len());
+        assert_eq!("subscription", sent[0].0);
+        assert_eq!("unsubscribe", sent[3].0);
+    }
+
+    /// A subscription stream that errs forwards that error to the client as a `subscription`
+    /// notification carrying an `error` member, instead of the error silently vanishing with
+    /// nothing but the final `unsubscribe`.
+    #[test]
+    fn subscription_stream_error_is_forwarded() {
+        let (ctl, _dropped, _killed, outgoing) = ServerCtl::new();
+        let (id, handle) = ctl.open_subscription();
+        let items = stream::iter_result(vec![Ok(1u32), Err(RPCError::invalid_params("boom"))]);
+        abortable_stream(items, ctl.clone(), id, handle).wait().unwrap();
+        let sent = drain(outgoing);
+        assert_eq!(3, sent.len());
+        assert_eq!("subscription", sent[0].0);
+        assert_eq!("subscription", sent[1].0);
+        assert_eq!(-32602, sent[1].1["error"]["code"].as_i64().unwrap());
+        assert_eq!("boom", sent[1].1["error"]["message"].as_str().unwrap());
+        assert_eq!("unsubscribe", sent[2].0);
+    }
+
+    /// `terminate` must emit the final `unsubscribe` notification for every subscription still
+    /// open when the connection goes away, not just for ones closed via an explicit
+    /// `close_subscription` call while their map entry is still present.
+    #[test]
+    fn terminate_notifies_open_subscriptions() {
+        let (ctl, _dropped, _killed, outgoing) = ServerCtl::new();
+        let (id, _handle) = ctl.open_subscription();
+        ctl.terminate();
+        let sent = drain(outgoing);
+        assert_eq!(1, sent.len());
+        assert_eq!("unsubscribe", sent[0].0);
+        assert_eq!(id, sent[0].1["subscription"].as_u64().unwrap());
+    }
+
+    struct Sub;
+
+    impl Server for Sub {
+        type Success = u32;
+        type RPCCallResult = Result<u32, RPCError>;
+        type NotificationResult = Result<(), ()>;
+        type SubscriptionResult = Box<dyn Stream<Item = u32, Error = RPCError>>;
+        fn subscription(&self, _ctl: &ServerCtl, method: &str, _params: &Option<Value>)
+                        -> Option<Self::SubscriptionResult> {
+            match method {
+                "ticks" => Some(Box::new(stream::iter_ok(vec![1, 2, 3]))),
+                _ => None,
+            }
+        }
+    }
+
+    /// `Endpoint::subscribe` assigns an id via `ServerCtl::open_subscription` and drives the
+    /// stream through `abortable_stream`, instead of just boxing it.
+    #[test]
+    fn endpoint_subscribe_assigns_id_and_drives_forwarding() {
+        let (endpoint, _dropped, _killed, _outgoing) = Endpoint::new(Sub);
+        assert!(endpoint.subscribe("nope", &None).is_none());
+        let (id, driver) = endpoint.subscribe("ticks", &None).unwrap();
+        assert_eq!(0, id);
+        driver.wait().unwrap();
+    }
+
+    struct Echo;
+
+    impl Server for Echo {
+        type Success = u32;
+        type RPCCallResult = Result<u32, RPCError>;
+        type NotificationResult = Result<(), ()>;
+        type SubscriptionResult = stream::Empty<u32, RPCError>;
+        fn rpc(&self, ctl: &ServerCtl, method: &str, _params: &Option<Value>)
+               -> Option<Self::RPCCallResult> {
+            match method {
+                "deadline" => Some(Ok(ctl.deadline().map(|d| d.as_secs() as u32).unwrap_or(0))),
+                _ => None,
+            }
+        }
+    }
+
+    /// `Endpoint::call` registers the call under the given id and makes the deadline it was
+    /// given visible through `ServerCtl::deadline`, instead of just boxing the result.
+    #[test]
+    fn endpoint_call_is_registered_and_honors_deadline() {
+        let (endpoint, _dropped, _killed, _outgoing) = Endpoint::new(Echo);
+        assert!(endpoint.call(1, "nope", &None, None).is_none());
+        let result = endpoint.call(1, "deadline", &None, Some(Duration::from_secs(5)))
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(5, result);
+    }
+
+    struct LazyEcho;
+
+    impl Server for LazyEcho {
+        type Success = u32;
+        type RPCCallResult = Box<dyn Future<Item = u32, Error = RPCError>>;
+        type NotificationResult = Result<(), ()>;
+        type SubscriptionResult = stream::Empty<u32, RPCError>;
+        fn rpc(&self, ctl: &ServerCtl, method: &str, _params: &Option<Value>)
+               -> Option<Self::RPCCallResult> {
+            match method {
+                // Reads the deadline lazily, the first time the returned future is polled,
+                // rather than eagerly while `rpc` itself runs.
+                "deadline" => {
+                    let ctl = ctl.clone();
+                    Some(Box::new(::futures::future::lazy(move || {
+                        Ok(ctl.deadline().map(|d| d.as_secs() as u32).unwrap_or(0))
+                    })))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    /// Dispatching a second call while a first one is still in flight must not change what the
+    /// first call's own `ServerCtl` reports: each `RequestId` gets its own deadline slot, not a
+    /// single connection-wide one that the most recently dispatched call overwrites.
+    #[test]
+    fn concurrent_calls_have_independent_deadlines() {
+        let (endpoint, _dropped, _killed, _outgoing) = Endpoint::new(LazyEcho);
+        let call_a = endpoint.call(1, "deadline", &None, Some(Duration::from_secs(30))).unwrap();
+        let call_b = endpoint.call(2, "deadline", &None, None).unwrap();
+        assert_eq!(30, call_a.wait().unwrap());
+        assert_eq!(0, call_b.wait().unwrap());
+    }
+
+    struct Never;
+
+    impl Server for Never {
+        type Success = ();
+        type RPCCallResult = Box<dyn Future<Item = (), Error = RPCError>>;
+        type NotificationResult = Result<(), ()>;
+        type SubscriptionResult = stream::Empty<(), RPCError>;
+        fn rpc(&self, _ctl: &ServerCtl, method: &str, _params: &Option<Value>)
+               -> Option<Self::RPCCallResult> {
+            match method {
+                "wait" => Some(Box::new(::futures::future::empty())),
+                _ => None,
+            }
+        }
+    }
+
+    /// Cancelling the request id a still-running `Endpoint::call` was registered under drops the
+    /// call without ever resolving it, instead of leaving it to run to completion.
+    #[test]
+    fn endpoint_call_is_cancellable() {
+        let (endpoint, _dropped, _killed, _outgoing) = Endpoint::new(Never);
+        let call = endpoint.call(1, "wait", &None, None).unwrap();
+        endpoint.ctl().cancel_call(1);
+        assert_eq!(Err(RPCError::cancelled()), call.wait());
+    }
+}
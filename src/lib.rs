@@ -0,0 +1,27 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A JSON-RPC 2.0 library built on top of `futures`.
+//!
+//! See the [`Server`](server/trait.Server.html) trait for the entry point to implementing a
+//! server, and [`ServerCtl`](endpoint/struct.ServerCtl.html) for how a server talks back to its
+//! endpoint.
+
+extern crate futures;
+extern crate rmpv;
+extern crate serde;
+extern crate serde_json;
+
+pub mod codec;
+pub mod endpoint;
+pub mod message;
+pub mod router;
+pub mod server;
+
+pub use endpoint::ServerCtl;
+pub use message::RPCError;
+pub use server::Server;
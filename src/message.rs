@@ -0,0 +1,79 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! JSON-RPC 2.0 error values.
+//!
+//! [`RPCError`](struct.RPCError.html) is the error type [`Server`](../server/trait.Server.html)
+//! implementations resolve their futures to; the endpoint turns it into the `error` member of the
+//! JSON-RPC reply.
+
+use std::error::Error;
+use std::fmt;
+
+use serde_json::Value;
+
+/// A JSON-RPC 2.0 error object (`code`, `message` and optional `data`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RPCError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RPCError {
+    /// Builds an error with an arbitrary code, as allowed for application-defined errors.
+    pub fn new<M: Into<String>>(code: i64, message: M, data: Option<Value>) -> Self {
+        RPCError {
+            code,
+            message: message.into(),
+            data,
+        }
+    }
+    /// The standard `-32602 Invalid params` error.
+    ///
+    /// Returned automatically by [`RouterServer`](../router/struct.RouterServer.html) when the
+    /// client's params don't deserialize into the handler's argument type.
+    pub fn invalid_params<M: Into<String>>(message: M) -> Self {
+        RPCError::new(-32602, message, None)
+    }
+    /// The standard `-32601 Method not found` error.
+    pub fn method_not_found(method: &str) -> Self {
+        RPCError::new(-32601, format!("Method '{}' not found", method), None)
+    }
+    /// An error reported instead of a reply when a call is cancelled or times out.
+    ///
+    /// The endpoint never actually sends this to the client — a cancelled or timed out call gets
+    /// no reply at all. It's the error the call's future is resolved to internally, so it can be
+    /// composed with the usual `Future`/`IntoFuture` machinery.
+    pub fn cancelled() -> Self {
+        RPCError::new(-32000, "Call cancelled", None)
+    }
+    /// The numeric JSON-RPC error code.
+    pub fn code(&self) -> i64 {
+        self.code
+    }
+    /// The human readable error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    /// Extra, application-defined error data, if any.
+    pub fn data(&self) -> Option<&Value> {
+        self.data.as_ref()
+    }
+}
+
+impl fmt::Display for RPCError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} ({})", self.message, self.code)
+    }
+}
+
+impl Error for RPCError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A [`Server`](../server/trait.Server.html) implementation that routes by method name.
+//!
+//! Most `Server` implementations just `match` on the method name and deserialize the params by
+//! hand. [`RouterServer`](struct.RouterServer.html) does that bookkeeping for you: register a
+//! handler per method name and it takes care of dispatching to it and of turning a deserialization
+//! failure into the proper `-32602 Invalid params` error.
+
+use std::collections::HashMap;
+
+use futures::{Future, IntoFuture};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, from_value, to_value};
+
+use endpoint::ServerCtl;
+use message::RPCError;
+use server::Server;
+
+type BoxRPCFuture = Box<dyn Future<Item = Value, Error = RPCError>>;
+type BoxNotificationFuture = Box<dyn Future<Item = (), Error = ()>>;
+type RPCHandler = Box<dyn Fn(&ServerCtl, &Option<Value>) -> BoxRPCFuture>;
+type NotificationHandler = Box<dyn Fn(&ServerCtl, &Option<Value>) -> BoxNotificationFuture>;
+
+/// A [`Server`](../server/trait.Server.html) that dispatches to handlers registered by method
+/// name.
+///
+/// This is built incrementally with [`rpc_method`](#method.rpc_method) and
+/// [`notification`](#method.notification), then used as any other `Server`. Unlike hand-written
+/// `match` based servers, the params are deserialized for you, using `serde`; if that fails, the
+/// client gets back a proper `-32602 Invalid params` error instead of the handler having to deal
+/// with it. A method name that was never registered still makes the callbacks return `None`, so
+/// `RouterServer` composes with other `Server` implementations like any other server.
+#[derive(Default)]
+pub struct RouterServer {
+    methods: HashMap<String, RPCHandler>,
+    notifications: HashMap<String, NotificationHandler>,
+}
+
+impl RouterServer {
+    /// Creates an empty router, answering no method at all.
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Registers a handler for an RPC method.
+    ///
+    /// The handler receives the `ServerCtl` and the params, already deserialized into `P`. If
+    /// the client's params don't deserialize into `P`, the client gets a `-32602 Invalid params`
+    /// error without the handler being called.
+    pub fn rpc_method<P, R, F>(mut self, name: &str, handler: F) -> Self
+        where P: DeserializeOwned + 'static,
+              R: IntoFuture<Error = RPCError> + 'static,
+              R::Item: Serialize,
+              F: Fn(&ServerCtl, P) -> R + 'static
+    {
+        let wrapped = move |ctl: &ServerCtl, params: &Option<Value>| -> BoxRPCFuture {
+            let params = params.clone().unwrap_or(Value::Null);
+            match from_value::<P>(params) {
+                Ok(params) => {
+                    let future = handler(ctl, params)
+                        .into_future()
+                        .map(|result| {
+                            to_value(result)
+                                .expect("Your result type is not convertible to JSON, which is \
+                                         a bug")
+                        });
+                    Box::new(future)
+                }
+                Err(e) => Box::new(Err(RPCError::invalid_params(e.to_string())).into_future()),
+            }
+        };
+        self.methods.insert(name.to_owned(), Box::new(wrapped));
+        self
+    }
+    /// Registers a handler for a notification.
+    ///
+    /// As the client doesn't expect a reply, deserialization failures are simply swallowed (the
+    /// handler just isn't called).
+    pub fn notification<P, R, F>(mut self, name: &str, handler: F) -> Self
+        where P: DeserializeOwned + 'static,
+              R: IntoFuture<Item = (), Error = ()> + 'static,
+              F: Fn(&ServerCtl, P) -> R + 'static
+    {
+        let wrapped = move |ctl: &ServerCtl, params: &Option<Value>| -> BoxNotificationFuture {
+            let params = params.clone().unwrap_or(Value::Null);
+            match from_value::<P>(params) {
+                Ok(params) => Box::new(handler(ctl, params).into_future()),
+                Err(_) => Box::new(Ok(()).into_future()),
+            }
+        };
+        self.notifications.insert(name.to_owned(), Box::new(wrapped));
+        self
+    }
+}
+
+impl Server for RouterServer {
+    type Success = Value;
+    type RPCCallResult = BoxRPCFuture;
+    type NotificationResult = BoxNotificationFuture;
+    type SubscriptionResult = ::futures::stream::Empty<Value, RPCError>;
+    fn rpc(&self, ctl: &ServerCtl, method: &str, params: &Option<Value>)
+           -> Option<Self::RPCCallResult> {
+        self.methods.get(method).map(|handler| handler(ctl, params))
+    }
+    fn notification(&self, ctl: &ServerCtl, method: &str, params: &Option<Value>)
+                    -> Option<Self::NotificationResult> {
+        self.notifications.get(method).map(|handler| handler(ctl, params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_params(a: i64, b: i64) -> Option<Value> {
+        Some(Value::from(vec![Value::from(a), Value::from(b)]))
+    }
+
+    /// A registered method is dispatched to and its params deserialized.
+    #[test]
+    fn dispatch_rpc() {
+        let router = RouterServer::new()
+            .rpc_method("add", |_ctl, (a, b): (i64, i64)| Ok(a + b));
+        let (ctl, _, _) = ServerCtl::new_test();
+        let params = add_params(1, 2);
+        let result = router.rpc(&ctl, "add", &params).unwrap().wait().unwrap();
+        assert_eq!(Value::from(3), result);
+        assert!(router.rpc(&ctl, "sub", &params).is_none());
+    }
+
+    /// Bad params on a *known* method produce an Invalid params error, not a panic or a None.
+    #[test]
+    fn invalid_params() {
+        let router = RouterServer::new()
+            .rpc_method("add", |_ctl, (a, b): (i64, i64)| Ok(a + b));
+        let (ctl, _, _) = ServerCtl::new_test();
+        let params = add_params(1, 2);
+        let bad_params = Some(Value::from(vec![Value::from("not a number"), params.unwrap()[1].clone()]));
+        assert!(router.rpc(&ctl, "add", &bad_params).unwrap().wait().is_err());
+    }
+
+    /// A registered notification is dispatched to and its params deserialized.
+    #[test]
+    fn dispatch_notification() {
+        let seen = ::std::rc::Rc::new(::std::cell::Cell::new(0i64));
+        let handler_seen = seen.clone();
+        let router = RouterServer::new()
+            .notification("add", move |_ctl, (a, b): (i64, i64)| {
+                handler_seen.set(a + b);
+                Ok::<_, ()>(())
+            });
+        let (ctl, _, _) = ServerCtl::new_test();
+        let params = add_params(1, 2);
+        Server::notification(&router, &ctl, "add", &params).unwrap().wait().unwrap();
+        assert_eq!(3, seen.get());
+        assert!(Server::notification(&router, &ctl, "sub", &params).is_none());
+    }
+
+    /// Bad params on a *known* notification are swallowed -- the handler just isn't called --
+    /// since the client isn't expecting a reply to act on.
+    #[test]
+    fn notification_invalid_params_is_swallowed() {
+        let called = ::std::rc::Rc::new(::std::cell::Cell::new(false));
+        let handler_called = called.clone();
+        let router = RouterServer::new()
+            .notification("add", move |_ctl, (_a, _b): (i64, i64)| {
+                handler_called.set(true);
+                Ok::<_, ()>(())
+            });
+        let (ctl, _, _) = ServerCtl::new_test();
+        let bad_params = Some(Value::from(vec![Value::from("not a number"), Value::from(2)]));
+        assert!(Server::notification(&router, &ctl, "add", &bad_params).unwrap().wait().is_ok());
+        assert!(!called.get());
+    }
+}
@@ -11,10 +11,14 @@
 //! here. Furthermore, some helpers for convenient creation and composition of servers are
 //! available. Note that not all of these helpers are necessarily zero-cost, at least at this time.
 
-use futures::{Future, IntoFuture};
+use std::marker::PhantomData;
+
+use futures::{Future, IntoFuture, Stream};
+use futures::stream;
 use serde::Serialize;
-use serde_json::{Value, to_value};
+use serde_json::Value;
 
+use codec::{Codec, DecodeError, DecodedCall, JsonCodec};
 use endpoint::ServerCtl;
 use message::RPCError;
 
@@ -34,6 +38,19 @@ pub trait Server {
     ///
     /// Once the future resolves, the value or error is sent to the client as the reply. The reply
     /// is wrapped automatically.
+    ///
+    /// The future the endpoint hands back for this call stops being polled once the call is
+    /// cancelled, through [`ServerCtl::cancel_call`](../endpoint/struct.ServerCtl.html#method.cancel_call)
+    /// with this call's request id or through the connection's `terminate`/`kill`: it is then
+    /// dropped without being polled to completion and resolves to `RPCError::cancelled` instead of
+    /// a reply. The endpoint does not recognize a cancellation notification from the client, nor
+    /// notice a dropped connection, by itself; those only cancel the call once something calls
+    /// `cancel_call` or `terminate` for them (a `notification` handler can call `cancel_call`).
+    /// This is explicit cancellation only -- there is no timeout enforcement here, so a
+    /// future that never resolves and is never cancelled is driven forever. Implementers that
+    /// hold a [`ServerCtl`](../endpoint/struct.ServerCtl.html) can call its `deadline` accessor
+    /// to read back the duration the call was dispatched with (advisory only, not a countdown)
+    /// and bail out of long-running work early on their own if they want actual enforcement.
     type RPCCallResult: IntoFuture<Item = Self::Success, Error = RPCError> + 'static;
     /// The result of the RPC call.
     ///
@@ -41,6 +58,17 @@ pub trait Server {
     /// thrown away and therefore (). However, it still makes sense to distinguish success and
     /// error.
     type NotificationResult: IntoFuture<Item = (), Error = ()> + 'static;
+    /// The stream of values pushed to a subscriber.
+    ///
+    /// Each item produced by the stream is wrapped into a notification carrying the
+    /// subscription id and sent to the client. The endpoint polls the stream until it ends on
+    /// its own, resolves to an error, or the subscription is closed (the client unsubscribed, or
+    /// its connection went away). If the stream errs, that error is forwarded to the client as a
+    /// `subscription` notification carrying an `error` member instead of `result`, so a failing
+    /// subscription isn't silently indistinguishable from one that just ran out of items. Either
+    /// way, a final unsubscribe notification is sent and the stream is dropped, so a forgotten
+    /// subscriber never leaves a task spinning.
+    type SubscriptionResult: Stream<Item = Self::Success, Error = RPCError> + 'static;
     /// Called when the client requests something.
     ///
     /// This is a callback from the [endpoint](struct.Endpoint.html) when the client requests
@@ -63,6 +91,26 @@ pub trait Server {
                     -> Option<Self::NotificationResult> {
         None
     }
+    /// Called when the client subscribes to something.
+    ///
+    /// This is the pub/sub counterpart of [`rpc`](#tymethod.rpc): instead of resolving once,
+    /// the returned stream is kept alive for as long as the subscription lives and each item it
+    /// produces is pushed to the client as a notification. The reply to the originating call is
+    /// the subscription id assigned by the endpoint.
+    ///
+    /// By convention, unsubscribing is just another call (commonly named `unsubscribe` or
+    /// `<method>_unsubscribe`), handled through the ordinary [`rpc`](#tymethod.rpc) callback,
+    /// whose implementation should call
+    /// [`ServerCtl::close_subscription`](../endpoint/struct.ServerCtl.html#method.close_subscription)
+    /// with the id it was given. The endpoint invokes `close_subscription` on the server's
+    /// behalf as well, whenever the stream terminates by itself or the client's connection is
+    /// dropped.
+    ///
+    /// As with `rpc`, returning `None` means the method is unknown, allowing composition.
+    fn subscription(&self, _ctl: &ServerCtl, _method: &str, _params: &Option<Value>)
+                    -> Option<Self::SubscriptionResult> {
+        None
+    }
     /// Called when the endpoint is initialized.
     ///
     /// It provides a default empty implementation, which can be overriden to hook onto the
@@ -81,6 +129,7 @@ impl Server for Empty {
     type Success = ();
     type RPCCallResult = Result<(), RPCError>;
     type NotificationResult = Result<(), ()>;
+    type SubscriptionResult = stream::Empty<(), RPCError>;
     fn initialized(&self, ctl: &ServerCtl) {
         ctl.terminate();
     }
@@ -90,36 +139,85 @@ impl Server for Empty {
 ///
 /// This server wraps another server and converts it into a common ground, so multiple different
 /// servers can be used as trait objects. Basically, it boxes the futures it returns and converts
-/// the result into `serde_json::Value`. It can then be used together with
-/// [`ServerChain`](struct.ServerChain.html) easilly. Note that this conversion incurs
-/// runtime costs.
-pub struct AbstractServer<S: Server>(S);
+/// the result into the value type of a [`Codec`](../codec/trait.Codec.html) (JSON by default, see
+/// [`JsonCodec`](../codec/struct.JsonCodec.html)). It can then be composed with other `Server`
+/// implementations easilly. Note that this conversion incurs runtime costs.
+///
+/// Picking [`MsgpackCodec`](../codec/struct.MsgpackCodec.html) instead of the default boxes
+/// replies, notifications and subscription items as `rmpv::Value` rather than JSON. The wrapped
+/// `Server` itself still only ever sees params as JSON, so driving it over a raw wire frame in
+/// the codec's own format goes through [`dispatch_rpc`](#method.dispatch_rpc),
+/// [`dispatch_notification`](#method.dispatch_notification) or
+/// [`dispatch_subscription`](#method.dispatch_subscription) instead of the plain `Server` methods
+/// directly: they take a [`DecodedCall`](../codec/struct.DecodedCall.html) in the codec's native
+/// representation, convert its params to JSON via
+/// [`Codec::params_to_json`](../codec/trait.Codec.html#tymethod.params_to_json), and only then
+/// call through to the wrapped server. This is what actually lets a `Server` speak
+/// MessagePack-RPC end to end, without hand-rolling the params transcode outside the crate.
+pub struct AbstractServer<S: Server, C: Codec = JsonCodec>(S, PhantomData<C>);
 
-impl<S: Server> AbstractServer<S> {
-    /// Wraps another server into an abstract server.
+impl<S: Server> AbstractServer<S, JsonCodec> {
+    /// Wraps another server into an abstract server, boxing over JSON values.
     pub fn new(server: S) -> Self {
-        AbstractServer(server)
+        AbstractServer(server, PhantomData)
+    }
+}
+
+impl<S: Server, C: Codec> AbstractServer<S, C> {
+    /// Wraps another server into an abstract server, boxing over the given codec's value type.
+    ///
+    /// Use this instead of [`new`](#method.new) to pick a codec other than the default
+    /// [`JsonCodec`](../codec/struct.JsonCodec.html), e.g.
+    /// `AbstractServer::<_, MsgpackCodec>::with_codec(server)`.
+    pub fn with_codec(server: S) -> Self {
+        AbstractServer(server, PhantomData)
     }
     /// Unwraps the abstract server and provides the one inside back.
     pub fn into_inner(self) -> S {
         self.0
     }
+    /// Dispatches a decoded RPC call to the wrapped server.
+    ///
+    /// `call.params`, in the codec's native representation, is converted to JSON via
+    /// [`Codec::params_to_json`](../codec/trait.Codec.html#tymethod.params_to_json) before the
+    /// wrapped server's [`Server::rpc`](trait.Server.html#tymethod.rpc) ever sees it. A
+    /// conversion failure is reported as `Err` without looking at whether the method is even
+    /// known, same as a malformed frame would be at the `Codec::decode` stage; `Ok(None)` still
+    /// means "method not known", exactly as plain `Server::rpc` does.
+    pub fn dispatch_rpc(&self, ctl: &ServerCtl, call: DecodedCall<C::Value>)
+                         -> Result<Option<<Self as Server>::RPCCallResult>, DecodeError> {
+        let params = call.params.map(C::params_to_json).transpose()?;
+        Ok(self.rpc(ctl, &call.method, &params))
+    }
+    /// Dispatches a decoded notification to the wrapped server.
+    ///
+    /// See [`dispatch_rpc`](#method.dispatch_rpc) for how `call.params` is converted.
+    pub fn dispatch_notification(&self, ctl: &ServerCtl, call: DecodedCall<C::Value>)
+                                  -> Result<Option<<Self as Server>::NotificationResult>, DecodeError> {
+        let params = call.params.map(C::params_to_json).transpose()?;
+        Ok(self.notification(ctl, &call.method, &params))
+    }
+    /// Dispatches a decoded subscription request to the wrapped server.
+    ///
+    /// See [`dispatch_rpc`](#method.dispatch_rpc) for how `call.params` is converted.
+    pub fn dispatch_subscription(&self, ctl: &ServerCtl, call: DecodedCall<C::Value>)
+                                  -> Result<Option<<Self as Server>::SubscriptionResult>, DecodeError> {
+        let params = call.params.map(C::params_to_json).transpose()?;
+        Ok(self.subscription(ctl, &call.method, &params))
+    }
 }
 
-impl<S: Server> Server for AbstractServer<S> {
-    type Success = Value;
-    type RPCCallResult = Box<Future<Item = Value, Error = RPCError>>;
-    type NotificationResult = Box<Future<Item = (), Error = ()>>;
+impl<S: Server, C: Codec> Server for AbstractServer<S, C> {
+    type Success = C::Value;
+    type RPCCallResult = Box<dyn Future<Item = C::Value, Error = RPCError>>;
+    type NotificationResult = Box<dyn Future<Item = (), Error = ()>>;
+    type SubscriptionResult = Box<dyn Stream<Item = C::Value, Error = RPCError>>;
     fn rpc(&self, ctl: &ServerCtl, method: &str, params: &Option<Value>)
            -> Option<Self::RPCCallResult> {
         self.0
             .rpc(ctl, method, params)
-            .map(|f| -> Box<Future<Item = Value, Error = RPCError>> {
-                let future = f.into_future()
-                    .map(|result| {
-                        to_value(result)
-                            .expect("Your result type is not convertible to JSON, which is a bug")
-                    });
+            .map(|f| -> Box<dyn Future<Item = C::Value, Error = RPCError>> {
+                let future = f.into_future().map(|result| C::to_value(result));
                 Box::new(future)
             })
     }
@@ -129,7 +227,15 @@ impl<S: Server> Server for AbstractServer<S> {
         // the outside, so we need to declare it manually :-(
         self.0
             .notification(ctl, method, params)
-            .map(|f| -> Box<Future<Item = (), Error = ()>> { Box::new(f.into_future()) })
+            .map(|f| -> Box<dyn Future<Item = (), Error = ()>> { Box::new(f.into_future()) })
+    }
+    fn subscription(&self, ctl: &ServerCtl, method: &str, params: &Option<Value>)
+                    -> Option<Self::SubscriptionResult> {
+        self.0
+            .subscription(ctl, method, params)
+            .map(|s| -> Box<dyn Stream<Item = C::Value, Error = RPCError>> {
+                Box::new(s.map(|result| C::to_value(result)))
+            })
     }
     fn initialized(&self, ctl: &ServerCtl) {
         self.0.initialized(ctl)
@@ -151,6 +257,7 @@ mod tests {
         for method in ["method", "notification", "check"].iter() {
             assert!(server.rpc(&ctl, method, &None).is_none());
             assert!(server.notification(&ctl, method, &None).is_none());
+            assert!(server.subscription(&ctl, method, &None).is_none());
         }
         // It terminates the ctl on the server side on initialization
         server.initialized(&ctl);
@@ -163,6 +270,7 @@ mod tests {
         serial: Cell<usize>,
         rpc: RefCell<Vec<usize>>,
         notification: RefCell<Vec<usize>>,
+        subscription: RefCell<Vec<usize>>,
         initialized: RefCell<Vec<usize>>,
     }
 
@@ -178,6 +286,7 @@ mod tests {
         type Success = bool;
         type RPCCallResult = Result<bool, RPCError>;
         type NotificationResult = Result<(), ()>;
+        type SubscriptionResult = stream::Empty<bool, RPCError>;
         fn rpc(&self, _ctl: &ServerCtl, method: &str, params: &Option<Value>)
                -> Option<Self::RPCCallResult> {
             self.update(&self.rpc);
@@ -196,6 +305,15 @@ mod tests {
                 _ => None,
             }
         }
+        fn subscription(&self, _ctl: &ServerCtl, method: &str, params: &Option<Value>)
+                        -> Option<Self::SubscriptionResult> {
+            self.update(&self.subscription);
+            assert!(params.is_none());
+            match method {
+                "sub" => Some(stream::empty()),
+                _ => None,
+            }
+        }
         fn initialized(&self, _ctl: &ServerCtl) {
             self.update(&self.initialized);
         }
@@ -219,16 +337,85 @@ mod tests {
             .unwrap()
             .wait()
             .unwrap();
+        let sub_result = abstract_server.subscription(&ctl, "sub", &None)
+            .unwrap()
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(Vec::<Value>::new(), sub_result);
         assert!(abstract_server.rpc(&ctl, "another", &None).is_none());
         assert!(abstract_server.notification(&ctl, "another", &None).is_none());
+        assert!(abstract_server.subscription(&ctl, "another", &None).is_none());
         abstract_server.initialized(&ctl);
         let log_server = abstract_server.into_inner();
         let expected = LogServer {
-            serial: Cell::new(5),
-            rpc: RefCell::new(vec![1, 3]),
-            notification: RefCell::new(vec![2, 4]),
-            initialized: RefCell::new(vec![5]),
+            serial: Cell::new(7),
+            rpc: RefCell::new(vec![1, 4]),
+            notification: RefCell::new(vec![2, 5]),
+            subscription: RefCell::new(vec![3, 6]),
+            initialized: RefCell::new(vec![7]),
         };
         assert_eq!(expected, log_server);
     }
-}
\ No newline at end of file
+
+    /// `AbstractServer` picks up `MsgpackCodec` and boxes over `rmpv::Value` instead of JSON.
+    #[test]
+    fn abstract_server_msgpack() {
+        use codec::MsgpackCodec;
+
+        let log_server = LogServer::default();
+        let abstract_server = AbstractServer::<_, MsgpackCodec>::with_codec(log_server);
+        let (ctl, _, _) = ServerCtl::new_test();
+        let rpc_result = abstract_server.rpc(&ctl, "test", &None)
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(::rmpv::Value::Boolean(true), rpc_result);
+    }
+
+    /// A server that echoes back the params it was called with, as JSON.
+    struct EchoServer;
+
+    impl Server for EchoServer {
+        type Success = Value;
+        type RPCCallResult = Result<Value, RPCError>;
+        type NotificationResult = Result<(), ()>;
+        type SubscriptionResult = stream::Empty<Value, RPCError>;
+        fn rpc(&self, _ctl: &ServerCtl, method: &str, params: &Option<Value>)
+               -> Option<Self::RPCCallResult> {
+            match method {
+                "echo" => Some(Ok(params.clone().unwrap_or(Value::Null))),
+                _ => None,
+            }
+        }
+    }
+
+    /// `AbstractServer::dispatch_rpc` converts a decoded MessagePack call's `rmpv::Value` params
+    /// into JSON centrally before the wrapped `Server` ever sees them -- the actual end-to-end
+    /// MessagePack-RPC path this module's docs describe.
+    #[test]
+    fn abstract_server_msgpack_dispatch_rpc_converts_params() {
+        use codec::MsgpackCodec;
+
+        let mut input = Vec::new();
+        let frame = ::rmpv::Value::Map(vec![
+            (::rmpv::Value::from("method"), ::rmpv::Value::from("echo")),
+            (
+                ::rmpv::Value::from("params"),
+                ::rmpv::Value::Array(vec![::rmpv::Value::from(1), ::rmpv::Value::from(2)]),
+            ),
+        ]);
+        ::rmpv::encode::write_value(&mut input, &frame).unwrap();
+        let call = MsgpackCodec::decode(&input).unwrap();
+
+        let abstract_server = AbstractServer::<_, MsgpackCodec>::with_codec(EchoServer);
+        let (ctl, _, _) = ServerCtl::new_test();
+        let result = abstract_server.dispatch_rpc(&ctl, call)
+            .unwrap()
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(::rmpv::Value::Array(vec![::rmpv::Value::from(1), ::rmpv::Value::from(2)]),
+                   result);
+    }
+}
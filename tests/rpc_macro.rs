@@ -0,0 +1,81 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Exercises the `#[rpc]` expansion end to end: a trait with a method and a notification, called
+//! through the generated `<Trait>Rpc<T>` wrapper as a plain `Server`.
+
+extern crate futures;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate tokio_jsonrpc;
+extern crate tokio_jsonrpc_macros;
+
+use futures::Future;
+use serde_json::Value;
+use tokio_jsonrpc::Server;
+use tokio_jsonrpc::endpoint::ServerCtl;
+use tokio_jsonrpc_macros::rpc;
+
+#[rpc]
+trait Calculator {
+    #[method]
+    fn add(&self, a: i64, b: i64) -> Result<i64, tokio_jsonrpc::RPCError>;
+    #[notification]
+    fn ping(&self);
+}
+
+#[derive(Default)]
+struct Impl {
+    pinged: ::std::cell::Cell<bool>,
+}
+
+impl Calculator for Impl {
+    fn add(&self, a: i64, b: i64) -> Result<i64, tokio_jsonrpc::RPCError> {
+        Ok(a + b)
+    }
+    fn ping(&self) {
+        self.pinged.set(true);
+    }
+}
+
+fn params(a: i64, b: i64) -> Option<Value> {
+    let mut map = serde_json::Map::new();
+    map.insert("a".to_owned(), Value::from(a));
+    map.insert("b".to_owned(), Value::from(b));
+    Some(Value::Object(map))
+}
+
+/// A `#[method]` is dispatched to and its result serialized back into JSON.
+#[test]
+fn method_dispatch() {
+    let wrapper = CalculatorRpc(Impl::default());
+    let (ctl, _, _) = ServerCtl::new_test();
+    let result = wrapper.rpc(&ctl, "add", &params(1, 2)).unwrap().wait().unwrap();
+    assert_eq!(Value::from(3), result);
+    assert!(wrapper.rpc(&ctl, "sub", &params(1, 2)).is_none());
+}
+
+/// A `#[notification]` is dispatched to without expecting a reply.
+#[test]
+fn notification_dispatch() {
+    let wrapper = CalculatorRpc(Impl::default());
+    let (ctl, _, _) = ServerCtl::new_test();
+    wrapper.notification(&ctl, "ping", &None).unwrap().wait().unwrap();
+    assert!(wrapper.0.pinged.get());
+}
+
+/// Bad params on a *known* method produce an Invalid params error, not a panic or a None.
+#[test]
+fn invalid_params() {
+    let wrapper = CalculatorRpc(Impl::default());
+    let (ctl, _, _) = ServerCtl::new_test();
+    let mut bad_params = serde_json::Map::new();
+    bad_params.insert("a".to_owned(), Value::from("not a number"));
+    bad_params.insert("b".to_owned(), Value::from(2));
+    let result = wrapper.rpc(&ctl, "add", &Some(Value::Object(bad_params))).unwrap().wait();
+    assert!(result.is_err());
+}
@@ -0,0 +1,207 @@
+// Copyright 2017 tokio-jsonrpc Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The `#[rpc]` attribute macro, companion to `tokio-jsonrpc`'s
+//! [`Server`](../tokio_jsonrpc/server/trait.Server.html) trait.
+//!
+//! Writing a `Server` impl by hand means matching on the method name, pulling the params apart
+//! and converting the result back into JSON yourself. This crate lets you describe the RPC
+//! surface as a plain trait instead:
+//!
+//! ```ignore
+//! #[rpc]
+//! trait Calculator {
+//!     #[method]
+//!     fn add(&self, a: i64, b: i64) -> Result<i64, RPCError>;
+//!     #[notification]
+//!     fn ping(&self);
+//! }
+//! ```
+//!
+//! `#[rpc]` leaves the trait itself untouched and, next to it, emits a `<Trait>Rpc<T>` wrapper
+//! that implements `Server` for any `T: Trait`. The wrapper deserializes positional or named
+//! `params` into the method's arguments, calls through to the trait implementation and serializes
+//! the result into `Self::Success`; a method name it doesn't recognize is reported as `None`, so
+//! the wrapper still composes with `AbstractServer` and other `Server` implementations like a
+//! hand-written `Server`.
+//!
+//! Each method or notification with arguments gets its own generated `Args` struct, deserialized
+//! via `::serde_derive::Deserialize` referenced by its full path, so using `#[rpc]` doesn't
+//! require `#[macro_use]` on `serde_derive` in the crate that writes the trait. The generated
+//! code names `::serde_derive`, `::serde_json`, `::futures` and `::tokio_jsonrpc` by absolute
+//! path and calls `Future::map`, so that crate needs those (and `serde`) as dependencies reachable
+//! from its root and `futures::Future` in scope where `#[rpc]` is used, as `tests/rpc_macro.rs`
+//! of `tokio-jsonrpc` has them.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use syn::{FnArg, ItemTrait, Pat, TraitItem, TraitItemFn};
+
+/// See the [crate-level docs](index.html).
+#[proc_macro_attribute]
+pub fn rpc(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input: ItemTrait = syn::parse(item).expect("#[rpc] can only be applied to a trait");
+    let expanded = expand(&input);
+    strip_helper_attrs(&mut input);
+    TokenStream::from(quote!(#input #expanded))
+}
+
+/// Removes the `#[method]`/`#[notification]` helper attributes from the trait before it's
+/// re-emitted: they're only meaningful to this macro, and left in place rustc would try (and
+/// fail) to resolve them as real attributes.
+fn strip_helper_attrs(input: &mut ItemTrait) {
+    for item in &mut input.items {
+        if let TraitItem::Fn(ref mut method) = *item {
+            method.attrs.retain(|a| !a.path().is_ident("method") && !a.path().is_ident("notification"));
+        }
+    }
+}
+
+/// A method annotated with `#[method]`: dispatched through `Server::rpc`.
+fn is_method(item: &TraitItemFn) -> bool {
+    item.attrs.iter().any(|a| a.path().is_ident("method"))
+}
+
+/// A method annotated with `#[notification]`: dispatched through `Server::notification`.
+fn is_notification(item: &TraitItemFn) -> bool {
+    item.attrs.iter().any(|a| a.path().is_ident("notification"))
+}
+
+fn args_of(method: &TraitItemFn) -> Vec<&syn::PatType> {
+    method.sig
+        .inputs
+        .iter()
+        .filter_map(|arg| match *arg {
+            FnArg::Typed(ref pat) => Some(pat),
+            FnArg::Receiver(_) => None,
+        })
+        .collect()
+}
+
+fn arg_name(pat: &syn::PatType) -> &syn::Ident {
+    match *pat.pat {
+        Pat::Ident(ref ident) => &ident.ident,
+        _ => panic!("#[rpc] methods must use plain identifier arguments"),
+    }
+}
+
+/// Generates the `<Trait>Rpc<T>` wrapper implementing `Server` for any `T: Trait`.
+fn expand(input: &ItemTrait) -> proc_macro2::TokenStream {
+    let trait_ident = &input.ident;
+    let wrapper_ident = syn::Ident::new(&format!("{}Rpc", trait_ident), trait_ident.span());
+
+    let methods: Vec<&TraitItemFn> = input.items
+        .iter()
+        .filter_map(|item| match *item {
+            TraitItem::Fn(ref m) => Some(m),
+            _ => None,
+        })
+        .collect();
+
+    let rpc_arms = methods.iter().filter(|m| is_method(m)).map(|m| {
+        let name = m.sig.ident.to_string();
+        let ident = &m.sig.ident;
+        let args = args_of(m);
+        let arg_names: Vec<_> = args.iter().map(|a| arg_name(a)).collect();
+        let arg_types: Vec<_> = args.iter().map(|a| &a.ty).collect();
+        let dispatch = if args.is_empty() {
+            quote! { let result = self.0.#ident(); }
+        } else {
+            quote! {
+                #[derive(::serde_derive::Deserialize)]
+                struct Args { #( #arg_names: #arg_types ),* }
+                let parsed: Args = match *params {
+                    Some(ref value) => match ::serde_json::from_value(value.clone()) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return Some(Box::new(::futures::future::err(
+                            ::tokio_jsonrpc::message::RPCError::invalid_params(e.to_string()),
+                        ))),
+                    },
+                    None => match ::serde_json::from_value(::serde_json::Value::Null) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return Some(Box::new(::futures::future::err(
+                            ::tokio_jsonrpc::message::RPCError::invalid_params(e.to_string()),
+                        ))),
+                    },
+                };
+                let result = self.0.#ident(#( parsed.#arg_names ),*);
+            }
+        };
+        quote! {
+            #name => {
+                #dispatch
+                Some(Box::new(::futures::IntoFuture::into_future(result).map(|item| {
+                    ::serde_json::to_value(item)
+                        .expect("Your result type is not convertible to JSON, which is a bug")
+                })))
+            }
+        }
+    });
+
+    let notification_arms = methods.iter().filter(|m| is_notification(m)).map(|m| {
+        let name = m.sig.ident.to_string();
+        let ident = &m.sig.ident;
+        let args = args_of(m);
+        let arg_names: Vec<_> = args.iter().map(|a| arg_name(a)).collect();
+        let arg_types: Vec<_> = args.iter().map(|a| &a.ty).collect();
+        let dispatch = if args.is_empty() {
+            quote! { self.0.#ident(); }
+        } else {
+            quote! {
+                #[derive(::serde_derive::Deserialize)]
+                struct Args { #( #arg_names: #arg_types ),* }
+                let parsed: Option<Args> = params.as_ref()
+                    .map(|value| ::serde_json::from_value(value.clone()))
+                    .unwrap_or_else(|| ::serde_json::from_value(::serde_json::Value::Null))
+                    .ok();
+                if let Some(parsed) = parsed {
+                    self.0.#ident(#( parsed.#arg_names ),*);
+                }
+            }
+        };
+        quote! {
+            #name => {
+                #dispatch
+                Some(Box::new(::futures::future::ok(())))
+            }
+        }
+    });
+
+    quote! {
+        /// Generated by `#[rpc]`: implements `Server` by dispatching to the methods of
+        /// the annotated trait.
+        pub struct #wrapper_ident<T>(pub T);
+
+        impl<T: #trait_ident> ::tokio_jsonrpc::server::Server for #wrapper_ident<T> {
+            type Success = ::serde_json::Value;
+            type RPCCallResult = Box<dyn futures::Future<Item = ::serde_json::Value,
+                                                          Error = ::tokio_jsonrpc::message::RPCError>>;
+            type NotificationResult = Box<dyn futures::Future<Item = (), Error = ()>>;
+            type SubscriptionResult = ::futures::stream::Empty<::serde_json::Value,
+                                                                 ::tokio_jsonrpc::message::RPCError>;
+            fn rpc(&self, _ctl: &::tokio_jsonrpc::endpoint::ServerCtl, method: &str,
+                   params: &Option<::serde_json::Value>) -> Option<Self::RPCCallResult> {
+                match method {
+                    #( #rpc_arms, )*
+                    _ => None,
+                }
+            }
+            fn notification(&self, _ctl: &::tokio_jsonrpc::endpoint::ServerCtl, method: &str,
+                            params: &Option<::serde_json::Value>) -> Option<Self::NotificationResult> {
+                match method {
+                    #( #notification_arms, )*
+                    _ => None,
+                }
+            }
+        }
+    }
+}